@@ -0,0 +1,140 @@
+use mongodb::bson::{Bson, Binary};
+use mongodb::bson::spec::BinarySubtype;
+use serde::{Deserialize, Serialize};
+
+use crate::error;
+use crate::error::MConfigError;
+
+// translates between a config value and the `Bson` stored in the `value` field of an entry,
+// so config can interop with non-Rust services that don't write native BSON
+pub trait ValueCodec<V>: Send + Sync {
+    fn encode(&self, value: &V) -> error::Result<Bson>;
+    fn decode(&self, bson: Bson) -> error::Result<V>;
+}
+
+// stores the value using its native BSON representation (the original, default behavior)
+pub struct NativeBsonCodec;
+
+impl<V: Serialize + for<'de> Deserialize<'de>> ValueCodec<V> for NativeBsonCodec {
+    fn encode(&self, value: &V) -> error::Result<Bson> {
+        mongodb::bson::to_bson(value).map_err(MConfigError::SerializationError)
+    }
+
+    fn decode(&self, bson: Bson) -> error::Result<V> {
+        mongodb::bson::from_bson(bson).map_err(MConfigError::DeserializationError)
+    }
+}
+
+fn expect_string(bson: Bson) -> error::Result<String> {
+    match bson {
+        Bson::String(text) => Ok(text),
+        other => Err(MConfigError::UnexpectedBsonType { expected: "string", found: other.element_type() }),
+    }
+}
+
+// stores the value JSON-encoded inside a single string field
+pub struct JsonStringCodec;
+
+impl<V: Serialize + for<'de> Deserialize<'de>> ValueCodec<V> for JsonStringCodec {
+    fn encode(&self, value: &V) -> error::Result<Bson> {
+        serde_json::to_string(value).map(Bson::String).map_err(MConfigError::JsonError)
+    }
+
+    fn decode(&self, bson: Bson) -> error::Result<V> {
+        let text = expect_string(bson)?;
+        serde_json::from_str(&text).map_err(MConfigError::JsonError)
+    }
+}
+
+// stores the value TOML-encoded inside a single string field; the value must serialize to a
+// TOML table (a struct or map), a bare scalar or sequence fails to encode
+pub struct TomlStringCodec;
+
+impl<V: Serialize + for<'de> Deserialize<'de>> ValueCodec<V> for TomlStringCodec {
+    fn encode(&self, value: &V) -> error::Result<Bson> {
+        toml::to_string(value).map(Bson::String).map_err(MConfigError::TomlSerializeError)
+    }
+
+    fn decode(&self, bson: Bson) -> error::Result<V> {
+        let text = expect_string(bson)?;
+        toml::from_str(&text).map_err(MConfigError::TomlDeserializeError)
+    }
+}
+
+// stores the value as a length-prefixed MessagePack payload, for schema-flexible binary
+// storage when large configs shouldn't pay BSON/JSON's size overhead
+pub struct MsgPackBinaryCodec;
+
+impl<V: Serialize + for<'de> Deserialize<'de>> ValueCodec<V> for MsgPackBinaryCodec {
+    fn encode(&self, value: &V) -> error::Result<Bson> {
+        let payload = rmp_serde::to_vec(value).map_err(MConfigError::MsgPackSerializeError)?;
+        let mut framed = Vec::with_capacity(4 + payload.len());
+        framed.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        framed.extend_from_slice(&payload);
+        Ok(Bson::Binary(Binary { subtype: BinarySubtype::Generic, bytes: framed }))
+    }
+
+    fn decode(&self, bson: Bson) -> error::Result<V> {
+        let bytes = match bson {
+            Bson::Binary(binary) => binary.bytes,
+            other => return Err(MConfigError::UnexpectedBsonType { expected: "binary", found: other.element_type() }),
+        };
+        if bytes.len() < 4 {
+            return Err(MConfigError::CorruptBinaryValue);
+        }
+        let (len_bytes, rest) = bytes.split_at(4);
+        let len = u32::from_be_bytes(len_bytes.try_into().unwrap()) as usize;
+        let payload = rest.get(..len).ok_or(MConfigError::CorruptBinaryValue)?;
+        rmp_serde::from_slice(payload).map_err(MConfigError::MsgPackDeserializeError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_string_codec_round_trips() {
+        let codec = JsonStringCodec;
+        let bson = codec.encode(&"hello".to_string()).unwrap();
+        assert!(matches!(bson, Bson::String(_)));
+        let value: String = codec.decode(bson).unwrap();
+        assert_eq!(value, "hello");
+    }
+
+    #[test]
+    fn toml_string_codec_round_trips() {
+        let codec = TomlStringCodec;
+        let original: std::collections::BTreeMap<String, String> =
+            [("a".to_string(), "1".to_string()), ("b".to_string(), "2".to_string())].into_iter().collect();
+        let bson = codec.encode(&original).unwrap();
+        let value: std::collections::BTreeMap<String, String> = codec.decode(bson).unwrap();
+        assert_eq!(value, original);
+    }
+
+    #[test]
+    fn msgpack_binary_codec_round_trips() {
+        let codec = MsgPackBinaryCodec;
+        let bson = codec.encode(&42_i32).unwrap();
+        assert!(matches!(bson, Bson::Binary(_)));
+        let value: i32 = codec.decode(bson).unwrap();
+        assert_eq!(value, 42);
+    }
+
+    #[test]
+    fn msgpack_binary_codec_rejects_truncated_payload() {
+        let codec = MsgPackBinaryCodec;
+        let bytes = vec![0, 0, 0, 10]; // claims a 10-byte payload but carries none
+        let bson = Bson::Binary(Binary { subtype: BinarySubtype::Generic, bytes });
+        let result: error::Result<i32> = codec.decode(bson);
+        assert!(matches!(result, Err(MConfigError::CorruptBinaryValue)));
+    }
+
+    #[test]
+    fn msgpack_binary_codec_rejects_missing_length_prefix() {
+        let codec = MsgPackBinaryCodec;
+        let bson = Bson::Binary(Binary { subtype: BinarySubtype::Generic, bytes: vec![1, 2] });
+        let result: error::Result<i32> = codec.decode(bson);
+        assert!(matches!(result, Err(MConfigError::CorruptBinaryValue)));
+    }
+}
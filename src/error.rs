@@ -1,11 +1,30 @@
 use std::{fmt, result};
 use std::fmt::{Debug, Display, Formatter};
 
+use mongodb::bson::spec::ElementType;
+
 pub enum MConfigError {
     MongodbError(mongodb::error::Error),
     KeyNotExists {
         key: String
     },
+    VersionConflict {
+        key: String,
+        expected: u64,
+        found: Option<u64>,
+    },
+    SerializationError(mongodb::bson::ser::Error),
+    DeserializationError(mongodb::bson::de::Error),
+    JsonError(serde_json::Error),
+    TomlSerializeError(toml::ser::Error),
+    TomlDeserializeError(toml::de::Error),
+    MsgPackSerializeError(rmp_serde::encode::Error),
+    MsgPackDeserializeError(rmp_serde::decode::Error),
+    UnexpectedBsonType {
+        expected: &'static str,
+        found: ElementType,
+    },
+    CorruptBinaryValue,
 }
 
 pub type Result<T> = result::Result<T, MConfigError>;
@@ -25,6 +44,39 @@ impl Display for MConfigError {
             MConfigError::KeyNotExists { key } => {
                 write!(f, "key {} not found", key)
             }
+            MConfigError::VersionConflict { key, expected, found: Some(found) } => {
+                write!(f, "version conflict for key {}: expected version {}, found {}", key, expected, found)
+            }
+            MConfigError::VersionConflict { key, expected, found: None } => {
+                write!(f, "version conflict for key {}: expected version {}, but key no longer exists", key, expected)
+            }
+            MConfigError::SerializationError(e) => {
+                write!(f, "failed to serialize value {}", e)
+            }
+            MConfigError::DeserializationError(e) => {
+                write!(f, "failed to deserialize value {}", e)
+            }
+            MConfigError::JsonError(e) => {
+                write!(f, "failed to (de)serialize value as json: {}", e)
+            }
+            MConfigError::TomlSerializeError(e) => {
+                write!(f, "failed to serialize value as toml: {}", e)
+            }
+            MConfigError::TomlDeserializeError(e) => {
+                write!(f, "failed to deserialize value as toml: {}", e)
+            }
+            MConfigError::MsgPackSerializeError(e) => {
+                write!(f, "failed to serialize value as msgpack: {}", e)
+            }
+            MConfigError::MsgPackDeserializeError(e) => {
+                write!(f, "failed to deserialize value as msgpack: {}", e)
+            }
+            MConfigError::UnexpectedBsonType { expected, found } => {
+                write!(f, "expected a {} bson value but found {:?}", expected, found)
+            }
+            MConfigError::CorruptBinaryValue => {
+                write!(f, "stored binary value is missing or shorter than its length prefix")
+            }
         }
     }
 }
@@ -41,4 +93,4 @@ impl std::error::Error for MConfigError {}
 //     fn custom<T: Display>(msg: T) -> Self {
 //         Self::Message(msg.to_string())
 //     }
-// }
\ No newline at end of file
+// }
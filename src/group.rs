@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use mongodb::bson::doc;
+use mongodb::change_stream::event::ChangeStreamEvent;
+use mongodb::Collection;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, OnceCell, RwLock};
+use tokio::task::JoinHandle;
+
+use crate::error;
+use crate::error::MConfigError;
+use crate::handler::MConfigEntry;
+use crate::resumable_watch::ResumableWatch;
+
+pub use crate::resumable_watch::WatchStatus;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct GroupChangeResult<V> {
+    key: String,
+    value: V,
+}
+
+// loads and watches a whole set of keys through a single find and a single change stream,
+// instead of every key paying for its own cursor the way MConfigHandler does
+pub struct MConfigGroup<V: Send + Sync> {
+    keys: Vec<String>,
+    collection: Collection<MConfigEntry<V>>,
+    values: OnceCell<RwLock<HashMap<String, Arc<V>>>>,
+    watcher: OnceCell<JoinHandle<()>>,
+    senders: RwLock<HashMap<String, broadcast::Sender<Arc<V>>>>,
+    group_sender: broadcast::Sender<(String, Arc<V>)>,
+    resumable: ResumableWatch,
+}
+
+impl<V: Send + Sync> MConfigGroup<V> {
+    pub(crate) fn new(keys: Vec<String>, collection: Collection<MConfigEntry<V>>) -> Self {
+        let (group_sender, _) = broadcast::channel(16);
+        MConfigGroup {
+            keys,
+            collection,
+            values: OnceCell::new(),
+            watcher: OnceCell::new(),
+            senders: RwLock::new(HashMap::new()),
+            group_sender,
+            resumable: ResumableWatch::new(),
+        }
+    }
+}
+
+impl<V: Clone + Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin + 'static + Debug> MConfigGroup<V> {
+    // get a copy of the current value for one key in the group
+    pub async fn get(self: &Arc<MConfigGroup<V>>, key: &str) -> error::Result<Arc<V>> {
+        let values = self.values.get_or_try_init(|| self.init()).await?;
+        let guard = values.read().await;
+        guard.get(key).cloned().ok_or_else(|| MConfigError::KeyNotExists { key: key.to_string() })
+    }
+
+    // subscribe to updates for a single key in the group
+    pub async fn subscribe(self: &Arc<MConfigGroup<V>>, key: &str) -> broadcast::Receiver<Arc<V>> {
+        let mut senders = self.senders.write().await;
+        senders.entry(key.to_string()).or_insert_with(|| broadcast::channel(16).0).subscribe()
+    }
+
+    // subscribe to every update across the whole group, tagged with the key that changed
+    pub fn subscribe_all(self: &Arc<MConfigGroup<V>>) -> broadcast::Receiver<(String, Arc<V>)> {
+        self.group_sender.subscribe()
+    }
+
+    // subscribe to the watcher's connectivity status, so callers can tell when
+    // get() is serving cached values that may be stale because of a dropped change stream
+    pub async fn status_receiver(self: &Arc<MConfigGroup<V>>) -> tokio::sync::watch::Receiver<WatchStatus> {
+        self.resumable.status_receiver().await
+    }
+
+    async fn init(self: &Arc<MConfigGroup<V>>) -> Result<RwLock<HashMap<String, Arc<V>>>, MConfigError> {
+        self.watcher.get_or_init(|| async {
+            tokio::spawn({
+                let arc = self.clone();
+                async move { arc.watch().await }
+            })
+        }).await;
+        self.fetch_all().await.map(RwLock::new)
+    }
+
+    async fn fetch_all(self: &Arc<MConfigGroup<V>>) -> Result<HashMap<String, Arc<V>>, MConfigError> {
+        let filter = doc! {"key": {"$in": self.keys.clone()}};
+        let mut cursor = self.collection.find(filter).await.map_err(MConfigError::MongodbError)?;
+        let mut map = HashMap::with_capacity(self.keys.len());
+        while let Some(entry) = cursor.next().await {
+            let entry = entry.map_err(MConfigError::MongodbError)?;
+            map.insert(entry.key, Arc::new(entry.value));
+        }
+        Ok(map)
+    }
+
+    async fn store(self: &Arc<MConfigGroup<V>>, key: String, value: Arc<V>) {
+        if let Some(lock) = self.values.get() {
+            lock.write().await.insert(key.clone(), value.clone());
+        } else {
+            let mut map = HashMap::with_capacity(self.keys.len());
+            map.insert(key.clone(), value.clone());
+            self.values.get_or_init(|| async { RwLock::new(map) }).await;
+        }
+        if let Some(sender) = self.senders.read().await.get(&key) {
+            let _ = sender.send(value.clone());
+        }
+        let _ = self.group_sender.send((key, value));
+    }
+
+    async fn watch(self: &Arc<MConfigGroup<V>>) {
+        let pipeline = [
+            doc! {
+                "$match": {
+                    "operationType": "update",
+                    "fullDocument.key": {"$in": self.keys.clone()}
+                }
+            },
+            doc! {
+                "$project": {
+                    "operationType": 1_i32,
+                    "ns": 1_i32,
+                    "fullDocument": 1_i32
+                }
+            }
+        ];
+        let collection = self.collection.clone_with_type::<GroupChangeResult<V>>();
+        self.resumable.drive(&collection, &pipeline, |event| {
+            let arc = self.clone();
+            async move { arc.handle_stream_event(event).await }
+        }).await;
+    }
+
+    async fn handle_stream_event(self: &Arc<MConfigGroup<V>>, stream_event: ChangeStreamEvent<GroupChangeResult<V>>) {
+        if let Some(d) = stream_event.full_document {
+            self.store(d.key, Arc::new(d.value)).await;
+        }
+    }
+}
+
+impl<V: Send + Sync> Drop for MConfigGroup<V> {
+    fn drop(&mut self) {
+        if let Some(handler) = self.watcher.get() {
+            handler.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::options::ClientOptions;
+    use mongodb::Client;
+
+    use super::*;
+
+    // store() never touches the collection, so a client that's never actually asked to
+    // connect is enough to build a group for testing its in-memory dispatch
+    async fn test_group() -> Arc<MConfigGroup<String>> {
+        let options = ClientOptions::parse("mongodb://localhost:27017").await.unwrap();
+        let client = Client::with_options(options).unwrap();
+        let collection = client.database("mconfig_test").collection("config");
+        Arc::new(MConfigGroup::new(vec!["a".to_string(), "b".to_string()], collection))
+    }
+
+    #[tokio::test]
+    async fn store_dispatches_to_per_key_and_group_subscribers() {
+        let group = test_group().await;
+        let mut per_key = group.subscribe("a").await;
+        let mut all = group.subscribe_all();
+
+        group.store("a".to_string(), Arc::new("value".to_string())).await;
+
+        assert_eq!(*per_key.recv().await.unwrap(), "value");
+        let (key, value) = all.recv().await.unwrap();
+        assert_eq!(key, "a");
+        assert_eq!(*value, "value");
+
+        let values = group.values.get().unwrap().read().await;
+        assert_eq!(values.get("a").unwrap().as_str(), "value");
+    }
+
+    #[tokio::test]
+    async fn store_does_not_notify_other_keys_subscriber() {
+        let group = test_group().await;
+        let mut other_key = group.subscribe("b").await;
+
+        group.store("a".to_string(), Arc::new("value".to_string())).await;
+
+        assert!(other_key.try_recv().is_err());
+    }
+}
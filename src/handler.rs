@@ -1,45 +1,119 @@
 use std::fmt::Debug;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::{OnceCell, RwLock};
 use std::time::Duration;
-use mongodb::bson::doc;
-use mongodb::options::FullDocumentType;
+use mongodb::bson::{doc, Bson};
+use mongodb::change_stream::event::ChangeStreamEvent;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
 use mongodb::Collection;
 use tokio::task::JoinHandle;
+use crate::codec::ValueCodec;
 use crate::error;
 use crate::error::MConfigError;
-use futures::StreamExt;
-use mongodb::change_stream::event::ChangeStreamEvent;
+use crate::resumable_watch::ResumableWatch;
 
+pub use crate::resumable_watch::WatchStatus;
+
+// the shape of an entry when values are stored as native BSON (used by MConfigGroup, which
+// has no codec of its own and relies on the typed collection's serde deserialization)
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct MConfigEntry<V> {
+    pub(crate) key: String,
+    pub(crate) value: V,
+    pub(crate) version: u64,
+}
+
+// the on-the-wire shape MConfigHandler reads and writes; `value` is left as raw `Bson` so it
+// can be routed through a `ValueCodec` instead of always relying on V's native BSON shape
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct RawEntry {
     key: String,
-    value: V,
+    value: Bson,
+    version: u64,
 }
 
-pub struct MConfigHandler<V: Send + Sync> {
+// how the handler learns about updates to its key
+#[derive(Debug, Clone, Copy)]
+pub enum WatchMode {
+    // open a MongoDB change stream (requires a replica set or sharded cluster)
+    ChangeStream,
+    // periodically re-run find_one instead, for deployments without a replica set
+    Polling { interval: Duration },
+}
+
+impl Default for WatchMode {
+    fn default() -> Self {
+        WatchMode::ChangeStream
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HandlerOptions {
+    // after every successful fetch/update, the value is written here so init() can still
+    // serve a last-known-good value if MongoDB is unreachable on a later startup
+    pub disk_cache_path: Option<PathBuf>,
+    pub watch_mode: WatchMode,
+}
+
+pub struct MConfigHandler<V: Send + Sync, C> {
     pub(crate) key: String,
-    pub(crate) collection: Collection<MConfigEntry<V>>,
-    pub(crate) value: OnceCell<RwLock<Arc<V>>>,
+    pub(crate) collection: Collection<RawEntry>,
+    pub(crate) codec: C,
+    pub(crate) value: OnceCell<RwLock<CachedValue<V>>>,
     pub(crate) watcher: OnceCell<JoinHandle<()>>,
     pub(crate) sender: Option<tokio::sync::broadcast::Sender<Arc<V>>>,
+    pub(crate) resumable: ResumableWatch,
+    pub(crate) disk_cache_path: Option<PathBuf>,
+    pub(crate) watch_mode: WatchMode,
+}
+
+// the value currently believed to be in the database, alongside the version
+// it was read or written at, used as the optimistic-concurrency token for CAS updates
+pub(crate) struct CachedValue<V> {
+    version: u64,
+    value: Arc<V>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct MConfigChangeResult<V> {
-    value: V,
+struct ChangeResult {
+    value: Bson,
+    version: u64,
 }
 
+impl<V: Send + Sync, C> MConfigHandler<V, C> {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
 
-impl<V: Clone + Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin + 'static + Debug> MConfigHandler<V> {
+    pub(crate) fn new(key: String, collection: Collection<RawEntry>, codec: C, options: HandlerOptions) -> Self {
+        MConfigHandler {
+            key,
+            collection,
+            codec,
+            value: OnceCell::new(),
+            watcher: OnceCell::new(),
+            sender: Some(tokio::sync::broadcast::channel(16).0),
+            resumable: ResumableWatch::new(),
+            disk_cache_path: options.disk_cache_path,
+            watch_mode: options.watch_mode,
+        }
+    }
+}
+
+impl<V, C> MConfigHandler<V, C>
+    where
+        V: Clone + PartialEq + Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin + 'static + Debug,
+        C: ValueCodec<V> + Send + Sync + 'static,
+{
     // get a copy of current config
-    pub async fn get_value(self: &Arc<MConfigHandler<V>>) -> error::Result<Arc<V>> {
+    pub async fn get_value(self: &Arc<Self>) -> error::Result<Arc<V>> {
         let result = self.value.get_or_try_init(|| self.init()).await;
         match result {
             Ok(lock) => {
                 let guard = lock.read().await;
-                Ok(guard.clone())
+                Ok(guard.value.clone())
             }
             Err(e) => {
                 Err(e)
@@ -48,33 +122,169 @@ impl<V: Clone + Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin + 's
     }
 
     // create a event receiver, can be used to run code when config is changed
-    pub async fn create_new_receiver(self: &Arc<MConfigHandler<V>>) -> Option<tokio::sync::broadcast::Receiver<Arc<V>>> {
+    pub async fn create_new_receiver(self: &Arc<Self>) -> Option<tokio::sync::broadcast::Receiver<Arc<V>>> {
         self.sender.clone().map(|v| { v.subscribe() })
     }
 
-    async fn init(self: &Arc<MConfigHandler<V>>) -> Result<RwLock<Arc<V>>, MConfigError> {
+    // subscribe to the watcher's connectivity status, so callers can tell when
+    // get_value is serving a cached value that may be stale because of a dropped change stream
+    pub async fn status_receiver(self: &Arc<Self>) -> tokio::sync::watch::Receiver<WatchStatus> {
+        self.resumable.status_receiver().await
+    }
+
+    // overwrite the current value unconditionally, bumping the version
+    pub async fn set_value(self: &Arc<Self>, new: V) -> error::Result<()> {
+        let new_bson = self.codec.encode(&new)?;
+        let filter = doc! {"key": self.key.clone()};
+        let update = doc! {"$set": {"value": new_bson}, "$inc": {"version": 1_i64}};
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        match self.collection.find_one_and_update(filter, update).with_options(options).await {
+            Ok(Some(entry)) => {
+                self.store_cached(CachedValue { version: entry.version, value: Arc::new(new) }).await;
+                Ok(())
+            }
+            Ok(None) => {
+                Err(MConfigError::KeyNotExists { key: self.key.clone() })
+            }
+            Err(e) => {
+                Err(MConfigError::MongodbError(e))
+            }
+        }
+    }
+
+    // update the value only if it is still at the version we last observed
+    pub async fn compare_and_swap(self: &Arc<Self>, expected: &V, new: V) -> error::Result<()> {
+        let lock = self.value.get_or_try_init(|| self.init()).await?;
+        let observed_version = {
+            let guard = lock.read().await;
+            if guard.value.as_ref() != expected {
+                // the cached value is stale, not the key gone; `found` is exactly what we just
+                // read, distinct from the `found: None` of the find_one_and_update miss below
+                return Err(MConfigError::VersionConflict {
+                    key: self.key.clone(),
+                    expected: guard.version,
+                    found: Some(guard.version),
+                });
+            }
+            guard.version
+        };
+
+        let new_bson = self.codec.encode(&new)?;
+        let filter = doc! {"key": self.key.clone(), "version": observed_version as i64};
+        let update = doc! {"$set": {"value": new_bson}, "$inc": {"version": 1_i64}};
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        match self.collection.find_one_and_update(filter, update).with_options(options).await {
+            Ok(Some(entry)) => {
+                self.store_cached(CachedValue { version: entry.version, value: Arc::new(new) }).await;
+                Ok(())
+            }
+            Ok(None) => {
+                let found = match self.fetch_entry().await {
+                    Ok(entry) => Some(entry.version),
+                    Err(MConfigError::KeyNotExists { .. }) => None,
+                    Err(e) => return Err(e),
+                };
+                Err(MConfigError::VersionConflict {
+                    key: self.key.clone(),
+                    expected: observed_version,
+                    found,
+                })
+            }
+            Err(e) => {
+                Err(MConfigError::MongodbError(e))
+            }
+        }
+    }
+
+    async fn init(self: &Arc<Self>) -> Result<RwLock<CachedValue<V>>, MConfigError> {
         self.watcher.get_or_init(|| async {
             tokio::spawn({
                 let arc = self.clone();
                 async move {
-                    loop {
-                        let is_wait = arc.watch().await;
-                        if is_wait {
-                            // TODO: hardcode time
-                            tokio::time::sleep(Duration::from_secs(10)).await;
+                    match arc.watch_mode {
+                        WatchMode::ChangeStream => arc.watch().await,
+                        WatchMode::Polling { interval } => {
+                            loop {
+                                tokio::time::sleep(interval).await;
+                                arc.poll_once().await;
+                            }
                         }
                     }
                 }
             })
         }).await;
-        let result = self.fetch_value().await;
-        result.map(|v| RwLock::new(Arc::new(v)))
+        match self.fetch_value().await {
+            Ok(cached) => {
+                self.persist_to_disk(&cached).await;
+                // in polling mode nothing else will mark us Live until the first tick fires,
+                // which can be minutes away; the value we just fetched is already fresh
+                if matches!(self.watch_mode, WatchMode::Polling { .. }) {
+                    self.resumable.set_status(WatchStatus::Live).await;
+                }
+                Ok(RwLock::new(cached))
+            }
+            Err(e) => {
+                match self.load_from_disk().await {
+                    Some(cached) => Ok(RwLock::new(cached)),
+                    None => Err(e),
+                }
+            }
+        }
     }
 
-    async fn fetch_value(self: &Arc<MConfigHandler<V>>) -> Result<V, MConfigError> {
+    // re-run find_one and emit on the broadcast channel only if the value actually changed;
+    // used instead of a change stream when the deployment has no replica set to watch
+    async fn poll_once(self: &Arc<Self>) {
+        let fresh = match self.fetch_value().await {
+            Ok(fresh) => fresh,
+            Err(_) => {
+                self.resumable.set_status(WatchStatus::Reconnecting).await;
+                return;
+            }
+        };
+        self.resumable.set_status(WatchStatus::Live).await;
+        let changed = match self.value.get() {
+            Some(lock) => {
+                let guard = lock.read().await;
+                guard.value.as_ref() != fresh.value.as_ref()
+            }
+            None => true,
+        };
+        if !changed {
+            return;
+        }
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(fresh.value.clone());
+        }
+        self.store_cached(fresh).await;
+    }
+
+    // write the last-known-good value to disk so a later init() can boot degraded if
+    // MongoDB is unreachable; best-effort, write failures are not surfaced to callers
+    async fn persist_to_disk(self: &Arc<Self>, cached: &CachedValue<V>) {
+        let Some(path) = &self.disk_cache_path else {
+            return;
+        };
+        write_disk_cache(path, cached.value.as_ref()).await;
+    }
+
+    async fn load_from_disk(self: &Arc<Self>) -> Option<CachedValue<V>> {
+        let path = self.disk_cache_path.as_ref()?;
+        let value = read_disk_cache(path).await?;
+        // the on-disk cache doesn't know the remote version; 0 is always older than any
+        // real document, so a CAS attempted while running degraded will safely conflict
+        Some(CachedValue { version: 0, value: Arc::new(value) })
+    }
+
+    async fn fetch_entry(self: &Arc<Self>) -> Result<RawEntry, MConfigError> {
         match self.collection.find_one(doc! {"key":self.key.clone()}).await {
-            Ok(Some(task)) => {
-                Ok(task.value)
+            Ok(Some(entry)) => {
+                Ok(entry)
             }
             Ok(None) => {
                 Err(MConfigError::KeyNotExists { key: self.key.clone() })
@@ -85,8 +295,23 @@ impl<V: Clone + Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin + 's
         }
     }
 
-    // keep watching current config in database, update config in memory of the one in database changed
-    async fn watch(self: &Arc<MConfigHandler<V>>) -> bool {
+    async fn fetch_value(self: &Arc<Self>) -> Result<CachedValue<V>, MConfigError> {
+        let entry = self.fetch_entry().await?;
+        let value = self.codec.decode(entry.value)?;
+        Ok(CachedValue { version: entry.version, value: Arc::new(value) })
+    }
+
+    async fn store_cached(self: &Arc<Self>, cached: CachedValue<V>) {
+        self.persist_to_disk(&cached).await;
+        if let Some(lock) = self.value.get() {
+            *lock.write().await = cached;
+        } else {
+            self.value.get_or_init(|| async { RwLock::new(cached) }).await;
+        }
+    }
+
+    // keep watching current config in database, update config in memory if the one in database changed
+    async fn watch(self: &Arc<Self>) {
         let pipeline = [
             doc! {
                 "$match": {
@@ -104,63 +329,97 @@ impl<V: Clone + Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin + 's
                 }
             }
         ];
-        let collection = self.collection.clone_with_type::<MConfigChangeResult<V>>();
-        let mut change_stream = match collection
-            .watch()
-            .pipeline(pipeline)
-            .full_document(FullDocumentType::UpdateLookup)
-            .await
-        {
-            Ok(value) => { value }
-            Err(_) => {
-                return true;
-            }
-        };
-        loop {
-            tokio::select! {
-                // _=tokio::signal::ctrl_c()=>{
-                //     // stop the whole consumer
-                //     return false;
-                // }
-                _=tokio::time::sleep(Duration::from_secs(60))=>{
-                    if !change_stream.is_alive(){
-                        return false;
-                    }
-                }
-                stream_result=change_stream.next()=>{
-                    if let Some(Ok(stream_event))=stream_result{
-                        self.handle_stream_event(stream_event).await;
-                    }else{
-                        return false;
-                    }
-                }
-            }
-        }
+        let collection = self.collection.clone_with_type::<ChangeResult>();
+        self.resumable.drive(&collection, &pipeline, |event| {
+            let arc = self.clone();
+            async move { arc.handle_stream_event(event).await }
+        }).await;
     }
-    async fn handle_stream_event(self: &Arc<MConfigHandler<V>>, stream_event: ChangeStreamEvent<MConfigChangeResult<V>>) {
-        let value = match stream_event.full_document.map(|d| d.value) {
-            None => {
-                return;
-            }
-            Some(v) => {
-                Arc::new(v)
-            }
+
+    async fn handle_stream_event(self: &Arc<Self>, stream_event: ChangeStreamEvent<ChangeResult>) {
+        let Some(d) = stream_event.full_document else {
+            return;
+        };
+        let value = match self.codec.decode(d.value) {
+            Ok(value) => value,
+            // can't make sense of this update; keep serving the last good cached value
+            Err(_) => return,
         };
+        let cached = CachedValue { version: d.version, value: Arc::new(value) };
         if let Some(sender) = &self.sender {
-            let _ = sender.send(value.clone());
-        }
-        if let Some(lock) = self.value.get() {
-            *lock.write().await = value;
-        } else {
-            self.value.get_or_init(|| async { RwLock::new(value) }).await;
+            let _ = sender.send(cached.value.clone());
         }
+        self.store_cached(cached).await;
     }
 }
 
-impl<V: Send + Sync> Drop for MConfigHandler<V> {
+impl<V: Send + Sync, C> Drop for MConfigHandler<V, C> {
     fn drop(&mut self) {
         if let Some(handler) = self.watcher.get() {
             handler.abort();
         }
     }
 }
+
+// a process-wide counter so concurrent writers never share a staging file
+static TMP_SUFFIX: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+// atomically write `value` to `path` as JSON; best-effort, errors are swallowed by the caller
+async fn write_disk_cache<V: Serialize>(path: &PathBuf, value: &V) -> Option<()> {
+    let bytes = serde_json::to_vec(value).ok()?;
+    let suffix = TMP_SUFFIX.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let tmp_path = path.with_extension(format!("{suffix}.tmp"));
+    tokio::fs::write(&tmp_path, &bytes).await.ok()?;
+    tokio::fs::rename(&tmp_path, path).await.ok()
+}
+
+async fn read_disk_cache<V: for<'de> Deserialize<'de>>(path: &PathBuf) -> Option<V> {
+    let bytes = tokio::fs::read(path).await.ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn disk_cache_round_trips() {
+        let dir = std::env::temp_dir().join(format!("mconfig-test-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("cache.json");
+
+        write_disk_cache(&path, &"cached value".to_string()).await.unwrap();
+        let value: String = read_disk_cache(&path).await.unwrap();
+        assert_eq!(value, "cached value");
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn disk_cache_read_missing_file_returns_none() {
+        let path = std::env::temp_dir().join("mconfig-test-missing-cache.json");
+        let value: Option<String> = read_disk_cache(&path).await;
+        assert!(value.is_none());
+    }
+
+    #[tokio::test]
+    async fn concurrent_writes_do_not_corrupt_the_cache() {
+        let dir = std::env::temp_dir().join(format!("mconfig-test-concurrent-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("cache.json");
+
+        let writes = (0..16).map(|i| {
+            let path = path.clone();
+            tokio::spawn(async move { write_disk_cache(&path, &i).await })
+        });
+        for write in writes {
+            write.await.unwrap();
+        }
+
+        // whichever write landed last, the file must parse as a single complete value
+        let value: i32 = read_disk_cache(&path).await.unwrap();
+        assert!((0..16).contains(&value));
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
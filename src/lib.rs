@@ -1,27 +1,28 @@
 use std::fmt::Debug;
-use std::sync::{Arc};
+use std::sync::Arc;
 
 use mongodb::{Client, Collection};
-use mongodb::bson::{Bson, doc};
+use mongodb::bson::Bson;
 use mongodb::options::{ClientOptions, ResolverConfig};
 use serde::Deserialize;
 use serde::Serialize;
-use tokio::sync::RwLock;
 
-use crate::error::MConfigError;
+use crate::codec::ValueCodec;
+use crate::group::MConfigGroup;
+use crate::handler::{HandlerOptions, MConfigHandler};
 
+pub mod codec;
 pub mod error;
+pub mod group;
+pub mod handler;
+mod resumable_watch;
+#[cfg(feature = "server")]
+pub mod server;
 
 pub struct MConfigClient {
     collection: Collection<Bson>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct MConfigEntry<V> {
-    key: String,
-    value: V,
-}
-
 impl MConfigClient {
     pub async fn create<Conn: AsRef<str>, Name: AsRef<str>>(connection_str: Conn, collection_name: Name) -> Self {
         let mut client_options = if cfg!(windows) && connection_str.as_ref().contains("+srv") {
@@ -42,55 +43,30 @@ impl MConfigClient {
         }
     }
 
-    pub async fn get_handler<V: Serialize + for<'de> Deserialize<'de>, S: AsRef<str>>(self, key: S) -> Arc<MConfigHandler<V>> {
-        let handler = MConfigHandler {
-            key: key.as_ref().to_string(),
-            collection: self.collection.clone_with_type(),
-            value: Default::default(),
-        };
-        Arc::new(handler)
-    }
-}
-
-pub struct MConfigHandler<V> {
-    key: String,
-    collection: Collection<MConfigEntry<V>>,
-    value: RwLock<Option<Arc<V>>>,
-}
-
-
-impl<V: Clone + Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin> MConfigHandler<V> {
-    pub async fn init_value(self: &Arc<MConfigHandler<V>>) -> error::Result<Arc<V>> {
-        match self.collection.find_one(doc! {"key":self.key.clone()}, None).await {
-            Ok(Some(task)) => {
-                let arc = Arc::new(task.value);
-                let mut guard = self.value.write().await;
-                *guard = Some(arc.clone());
-                Ok(arc)
-            }
-            Ok(None) => {
-                Err(MConfigError::KeyNotExists { key: self.key.clone() })
-            }
-            Err(e) => {
-                Err(MConfigError::MongodbError(e))
-            }
-        }
+    pub async fn get_handler<V, C, S>(self, key: S, codec: C) -> Arc<MConfigHandler<V, C>>
+        where
+            V: Clone + PartialEq + Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin + 'static + Debug,
+            C: ValueCodec<V> + Send + Sync + 'static,
+            S: AsRef<str>,
+    {
+        self.get_handler_with_options(key, codec, HandlerOptions::default()).await
     }
 
-    pub async fn get_value(self: &Arc<MConfigHandler<V>>) -> error::Result<Arc<V>> {
-        let is_inited = self.is_inited().await;
-        if is_inited {
-            self.init_value().await
-        } else {
-            let guard = self.value.read().await;
-            Ok(guard.as_ref().unwrap().clone())
-        }
+    // like get_handler, but with control over the on-disk fallback cache and the watch
+    // strategy (change stream vs. polling) used to notice updates
+    pub async fn get_handler_with_options<V, C, S>(self, key: S, codec: C, options: HandlerOptions) -> Arc<MConfigHandler<V, C>>
+        where
+            V: Clone + PartialEq + Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin + 'static + Debug,
+            C: ValueCodec<V> + Send + Sync + 'static,
+            S: AsRef<str>,
+    {
+        Arc::new(MConfigHandler::new(key.as_ref().to_string(), self.collection.clone_with_type(), codec, options))
     }
 
-    async fn is_inited(self: &Arc<MConfigHandler<V>>) -> bool {
-        let guard = self.value.read().await;
-        let is_inited = guard.is_none();
-        is_inited
+    // load and watch several keys through a single find and a single change stream
+    pub async fn get_group<V: Clone + Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin + 'static + Debug>(self, keys: &[&str]) -> Arc<MConfigGroup<V>> {
+        let keys = keys.iter().map(|key| key.to_string()).collect();
+        Arc::new(MConfigGroup::new(keys, self.collection.clone_with_type()))
     }
 }
 
@@ -99,13 +75,14 @@ mod tests {
     use std::env;
 
     use super::*;
+    use crate::codec::NativeBsonCodec;
 
     #[tokio::test]
     async fn test_get_value() {
         let connection_str = env::var("MongoDbStr").unwrap();
         let collection_name = env::var("MongoDbCollection").unwrap();
         let client = MConfigClient::create(connection_str, collection_name).await;
-        let handler = client.get_handler::<String, _>("aaa").await;
+        let handler = client.get_handler::<String, _, _>("aaa", NativeBsonCodec).await;
         let value = handler.get_value().await;
         assert!(value.is_ok());
         assert_eq!(value.unwrap().as_str(), "1111");
@@ -0,0 +1,170 @@
+use std::future::Future;
+use std::time::Duration;
+
+use futures::StreamExt;
+use mongodb::bson::Document;
+use mongodb::change_stream::ChangeStream;
+use mongodb::change_stream::event::{ChangeStreamEvent, ResumeToken};
+use mongodb::options::FullDocumentType;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, OnceCell, RwLock};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+// whether the background watcher currently has a live change stream open, or is
+// between connection attempts and therefore serving a possibly stale cached value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchStatus {
+    Live,
+    Reconnecting,
+}
+
+// the resumable-change-stream machinery shared by MConfigHandler and MConfigGroup: tracks the
+// resume token and reconnect backoff, and drives a change stream to a dispatch closure
+pub(crate) struct ResumableWatch {
+    resume_token: RwLock<Option<ResumeToken>>,
+    backoff: RwLock<Duration>,
+    status: OnceCell<watch::Sender<WatchStatus>>,
+}
+
+impl ResumableWatch {
+    pub(crate) fn new() -> Self {
+        ResumableWatch {
+            resume_token: RwLock::new(None),
+            backoff: RwLock::new(INITIAL_BACKOFF),
+            status: OnceCell::new(),
+        }
+    }
+
+    pub(crate) async fn status_receiver(&self) -> watch::Receiver<WatchStatus> {
+        let sender = self.status.get_or_init(|| async { watch::channel(WatchStatus::Reconnecting).0 }).await;
+        sender.subscribe()
+    }
+
+    pub(crate) async fn set_status(&self, status: WatchStatus) {
+        let sender = self.status.get_or_init(|| async { watch::channel(WatchStatus::Reconnecting).0 }).await;
+        let _ = sender.send(status);
+    }
+
+    async fn reset_backoff(&self) {
+        *self.backoff.write().await = INITIAL_BACKOFF;
+    }
+
+    async fn double_backoff(&self) {
+        let mut guard = self.backoff.write().await;
+        *guard = next_backoff(*guard);
+    }
+
+    // open a change stream, resuming from the last token we observed so that events occurring
+    // while we were disconnected are replayed instead of silently dropped
+    async fn open_change_stream<T>(&self, collection: &Collection<T>, pipeline: &[Document]) -> mongodb::error::Result<ChangeStream<ChangeStreamEvent<T>>>
+        where T: Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin + 'static,
+    {
+        let token = self.resume_token.read().await.clone();
+        let Some(token) = token else {
+            return collection.watch().pipeline(pipeline.to_vec()).full_document(FullDocumentType::UpdateLookup).await;
+        };
+        let resumed = collection.watch()
+            .pipeline(pipeline.to_vec())
+            .full_document(FullDocumentType::UpdateLookup)
+            .resume_after(token.clone())
+            .await;
+        if resumed.is_ok() {
+            return resumed;
+        }
+        // resume_after rejects a token taken from an invalidate event; start_after accepts one,
+        // so retry from the same token with it (a token that aged out of the oplog fails here too)
+        collection.watch()
+            .pipeline(pipeline.to_vec())
+            .full_document(FullDocumentType::UpdateLookup)
+            .start_after(token)
+            .await
+    }
+
+    // open one change stream connection and dispatch every event to `on_event` until it drops
+    async fn watch<T, F, Fut>(&self, collection: &Collection<T>, pipeline: &[Document], mut on_event: F)
+        where
+            T: Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin + 'static,
+            F: FnMut(ChangeStreamEvent<T>) -> Fut,
+            Fut: Future<Output=()>,
+    {
+        let mut change_stream = match self.open_change_stream(collection, pipeline).await {
+            Ok(value) => value,
+            Err(_) => return,
+        };
+        self.set_status(WatchStatus::Live).await;
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(60)) => {
+                    if !change_stream.is_alive() {
+                        return;
+                    }
+                }
+                stream_result = change_stream.next() => {
+                    if let Some(Ok(stream_event)) = stream_result {
+                        on_event(stream_event).await;
+                        if let Some(token) = change_stream.resume_token() {
+                            *self.resume_token.write().await = Some(token);
+                        }
+                        self.reset_backoff().await;
+                    } else {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    // reconnect forever, backing off between attempts and reporting WatchStatus::Reconnecting
+    // while disconnected; intended to be driven from a spawned background task
+    pub(crate) async fn drive<T, F, Fut>(&self, collection: &Collection<T>, pipeline: &[Document], mut on_event: F)
+        where
+            T: Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin + 'static,
+            F: FnMut(ChangeStreamEvent<T>) -> Fut,
+            Fut: Future<Output=()>,
+    {
+        loop {
+            self.watch(collection, pipeline, &mut on_event).await;
+            self.set_status(WatchStatus::Reconnecting).await;
+            let wait = { *self.backoff.read().await };
+            tokio::time::sleep(wait).await;
+            self.double_backoff().await;
+        }
+    }
+}
+
+// double `current`, capped at MAX_BACKOFF, for the reconnect delay after a failed attempt
+fn next_backoff(current: Duration) -> Duration {
+    (current * 2).min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_backoff_doubles() {
+        assert_eq!(next_backoff(INITIAL_BACKOFF), INITIAL_BACKOFF * 2);
+        assert_eq!(next_backoff(Duration::from_secs(1)), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn next_backoff_caps_at_max() {
+        assert_eq!(next_backoff(MAX_BACKOFF), MAX_BACKOFF);
+        assert_eq!(next_backoff(MAX_BACKOFF / 2 + Duration::from_secs(1)), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn double_backoff_advances_and_reset_backoff_restores_initial() {
+        let watch = ResumableWatch::new();
+        assert_eq!(*watch.backoff.read().await, INITIAL_BACKOFF);
+
+        watch.double_backoff().await;
+        assert_eq!(*watch.backoff.read().await, INITIAL_BACKOFF * 2);
+
+        watch.reset_backoff().await;
+        assert_eq!(*watch.backoff.read().await, INITIAL_BACKOFF);
+    }
+}
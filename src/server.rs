@@ -0,0 +1,207 @@
+#![cfg(feature = "server")]
+
+// re-broadcasts MConfigHandler updates to remote clients over plain length-delimited TCP
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, RwLock};
+
+use crate::codec::ValueCodec;
+use crate::handler::MConfigHandler;
+
+// a publishable source of config updates, implemented for MConfigHandler so the server
+// doesn't need to know the concrete value type or storage codec of each published key
+pub trait FanOutSource: Send + Sync {
+    fn key(&self) -> &str;
+    fn current_value(self: Arc<Self>) -> Pin<Box<dyn Future<Output=Option<Vec<u8>>> + Send>>;
+    fn subscribe_updates(self: Arc<Self>) -> Pin<Box<dyn Future<Output=Option<mpsc::UnboundedReceiver<Vec<u8>>>> + Send>>;
+}
+
+impl<V, C> FanOutSource for MConfigHandler<V, C>
+    where
+        V: Clone + PartialEq + Send + Sync + Serialize + for<'de> Deserialize<'de> + Unpin + 'static + Debug,
+        C: ValueCodec<V> + Send + Sync + 'static,
+{
+    fn key(&self) -> &str {
+        self.key()
+    }
+
+    fn current_value(self: Arc<Self>) -> Pin<Box<dyn Future<Output=Option<Vec<u8>>> + Send>> {
+        Box::pin(async move {
+            let value = self.get_value().await.ok()?;
+            serde_json::to_vec(value.as_ref()).ok()
+        })
+    }
+
+    fn subscribe_updates(self: Arc<Self>) -> Pin<Box<dyn Future<Output=Option<mpsc::UnboundedReceiver<Vec<u8>>>> + Send>> {
+        Box::pin(async move {
+            let mut receiver = self.create_new_receiver().await?;
+            let (tx, rx) = mpsc::unbounded_channel();
+            tokio::spawn(async move {
+                while let Ok(value) = receiver.recv().await {
+                    let Ok(bytes) = serde_json::to_vec(value.as_ref()) else {
+                        continue;
+                    };
+                    if tx.send(bytes).is_err() {
+                        return;
+                    }
+                }
+            });
+            Some(rx)
+        })
+    }
+}
+
+pub struct FanOutServer {
+    sources: RwLock<HashMap<String, Arc<dyn FanOutSource>>>,
+    sinks: RwLock<HashMap<String, Vec<mpsc::UnboundedSender<Vec<u8>>>>>,
+}
+
+impl FanOutServer {
+    pub fn new() -> Arc<Self> {
+        Arc::new(FanOutServer {
+            sources: RwLock::new(HashMap::new()),
+            sinks: RwLock::new(HashMap::new()),
+        })
+    }
+
+    // register a handler's key for publishing; spawns a task that fans its broadcast
+    // updates out to every sink currently subscribed to that key
+    pub async fn publish(self: &Arc<Self>, source: Arc<dyn FanOutSource>) {
+        let key = source.key().to_string();
+        self.sources.write().await.insert(key.clone(), source.clone());
+        let server = self.clone();
+        tokio::spawn(async move {
+            let Some(mut updates) = source.subscribe_updates().await else {
+                return;
+            };
+            while let Some(bytes) = updates.recv().await {
+                server.broadcast(&key, bytes).await;
+            }
+        });
+    }
+
+    async fn broadcast(self: &Arc<Self>, key: &str, bytes: Vec<u8>) {
+        let mut sinks = self.sinks.write().await;
+        if let Some(subscribers) = sinks.get_mut(key) {
+            subscribers.retain(|sink| sink.send(bytes.clone()).is_ok());
+        }
+    }
+
+    pub async fn listen<A: tokio::net::ToSocketAddrs>(self: &Arc<Self>, addr: A) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move { server.handle_connection(stream).await });
+        }
+    }
+
+    async fn handle_connection(self: Arc<Self>, mut stream: TcpStream) {
+        let Ok(key) = read_frame_string(&mut stream).await else {
+            return;
+        };
+        let Some(source) = self.sources.read().await.get(&key).cloned() else {
+            return;
+        };
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        if let Some(bytes) = source.current_value().await {
+            if tx.send(bytes).is_err() {
+                return;
+            }
+        }
+        self.sinks.write().await.entry(key).or_default().push(tx);
+        while let Some(bytes) = rx.recv().await {
+            if write_frame(&mut stream, &bytes).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// a subscribed key is the only thing a client ever sends us; no legitimate one comes close to this
+const MAX_KEY_FRAME_BYTES: u32 = 64 * 1024;
+
+async fn read_frame_string(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut len_bytes = [0u8; 4];
+    stream.read_exact(&mut len_bytes).await?;
+    let len = u32::from_be_bytes(len_bytes);
+    if len > MAX_KEY_FRAME_BYTES {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "frame length exceeds maximum"));
+    }
+    let mut buf = vec![0u8; len as usize];
+    stream.read_exact(&mut buf).await?;
+    String::from_utf8(buf).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+async fn write_frame(stream: &mut TcpStream, bytes: &[u8]) -> std::io::Result<()> {
+    stream.write_all(&(bytes.len() as u32).to_be_bytes()).await?;
+    stream.write_all(bytes).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::Mutex;
+
+    use super::*;
+
+    // a FanOutSource whose update stream is driven directly by the test, so publish()'s
+    // continuous-delivery behavior can be exercised without a MongoDB-backed MConfigHandler
+    struct TestSource {
+        key: String,
+        initial: Vec<u8>,
+        updates: Mutex<Option<mpsc::UnboundedReceiver<Vec<u8>>>>,
+    }
+
+    impl FanOutSource for TestSource {
+        fn key(&self) -> &str {
+            &self.key
+        }
+
+        fn current_value(self: Arc<Self>) -> Pin<Box<dyn Future<Output=Option<Vec<u8>>> + Send>> {
+            Box::pin(async move { Some(self.initial.clone()) })
+        }
+
+        fn subscribe_updates(self: Arc<Self>) -> Pin<Box<dyn Future<Output=Option<mpsc::UnboundedReceiver<Vec<u8>>>> + Send>> {
+            Box::pin(async move { self.updates.lock().await.take() })
+        }
+    }
+
+    #[tokio::test]
+    async fn publish_delivers_updates_after_the_initial_snapshot() {
+        let server = FanOutServer::new();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let source = Arc::new(TestSource {
+            key: "k".to_string(),
+            initial: b"first".to_vec(),
+            updates: Mutex::new(Some(rx)),
+        });
+        server.publish(source).await;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = server.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            server.handle_connection(stream).await;
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        write_frame(&mut client, b"k").await.unwrap();
+
+        // the initial snapshot
+        assert_eq!(read_frame_string(&mut client).await.unwrap(), "first");
+
+        // a subsequent update pushed through the source's broadcast sender
+        tx.send(b"second".to_vec()).unwrap();
+        assert_eq!(read_frame_string(&mut client).await.unwrap(), "second");
+    }
+}